@@ -8,7 +8,7 @@ const COUNTER_TABLE_ID: TableId<(), u64> = TableId { id: 0, key: PhantomData, va
 
 fn main() -> anyhow::Result<()> {
     let mut store = Storage::new();
-    store.put_table_entry(&COUNTER_TABLE_ID, (), 3);
+    store.put_table_entry(&COUNTER_TABLE_ID, (), 3)?;
 
     let counter = store.borrow_table_entry_mut(&COUNTER_TABLE_ID, &())?;
     if *counter > 0 {
@@ -17,5 +17,9 @@ fn main() -> anyhow::Result<()> {
 
     println!("counter = {}", *counter);
 
+    // Without this, the decrement above would only live in the in-memory
+    // cache and never make it back into the byte store.
+    store.flush();
+
     Ok(())
 }
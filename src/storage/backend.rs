@@ -0,0 +1,293 @@
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use fs4::FileExt;
+
+use super::TableEntry;
+
+/// Where `Storage` durably keeps the serialized bytes for each entry.
+///
+/// Entries are kept in `TableEntry` order (table id, then order-preserving
+/// key encoding) so `range_prefix` can serve a range or prefix scan without
+/// a full scan. `MemoryBackend` is the default and keeps everything in a
+/// `BTreeMap`, matching `Storage`'s original in-memory-only behavior.
+/// `FileBackend` mirrors the same data to an append-only write-ahead log
+/// on disk.
+pub trait StorageBackend: Send + Sync {
+    fn get(&self, entry: &TableEntry) -> Option<Vec<u8>>;
+
+    fn contains(&self, entry: &TableEntry) -> bool;
+
+    fn put(&mut self, entry: TableEntry, bytes: Vec<u8>);
+
+    fn remove(&mut self, entry: &TableEntry) -> Option<Vec<u8>>;
+
+    /// All entries currently known to this backend, in no particular order.
+    fn keys(&self) -> Vec<TableEntry>;
+
+    /// Every `(entry, bytes)` pair in `table_id` whose encoded key starts
+    /// with `prefix`, in ascending key order.
+    fn range_prefix(&self, table_id: u64, prefix: &[u8]) -> Vec<(TableEntry, Vec<u8>)>;
+
+    /// Compacts and fsyncs durable storage, if applicable. The default
+    /// no-op is correct for backends, like `MemoryBackend`, with nothing
+    /// to compact.
+    fn checkpoint(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn range_prefix_in(
+    records: &BTreeMap<TableEntry, Vec<u8>>,
+    table_id: u64,
+    prefix: &[u8],
+) -> Vec<(TableEntry, Vec<u8>)> {
+    let lower = TableEntry { id: table_id, key: prefix.to_vec() };
+    records
+        .range(lower..)
+        .take_while(|(entry, _)| entry.id == table_id && entry.key.starts_with(prefix))
+        .map(|(entry, bytes)| (entry.clone(), bytes.clone()))
+        .collect()
+}
+
+#[derive(Default)]
+pub struct MemoryBackend {
+    records: BTreeMap<TableEntry, Vec<u8>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> MemoryBackend {
+        MemoryBackend::default()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn get(&self, entry: &TableEntry) -> Option<Vec<u8>> {
+        self.records.get(entry).cloned()
+    }
+
+    fn contains(&self, entry: &TableEntry) -> bool {
+        self.records.contains_key(entry)
+    }
+
+    fn put(&mut self, entry: TableEntry, bytes: Vec<u8>) {
+        self.records.insert(entry, bytes);
+    }
+
+    fn remove(&mut self, entry: &TableEntry) -> Option<Vec<u8>> {
+        self.records.remove(entry)
+    }
+
+    fn keys(&self) -> Vec<TableEntry> {
+        self.records.keys().cloned().collect()
+    }
+
+    fn range_prefix(&self, table_id: u64, prefix: &[u8]) -> Vec<(TableEntry, Vec<u8>)> {
+        range_prefix_in(&self.records, table_id, prefix)
+    }
+}
+
+/// A single write-ahead log entry: either a `(TableEntry, value bytes)`
+/// record, or a tombstone marking `entry` as removed.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct Record {
+    entry: TableEntry,
+    bytes: Vec<u8>,
+    deleted: bool,
+}
+
+/// Append-only, file-backed `StorageBackend`.
+///
+/// Every `put`/`remove` is mirrored to an in-memory cache and appended as a
+/// `Record` (a tombstone, for `remove`) to a log file on disk.
+/// `FileBackend::open` takes an advisory exclusive lock on that file for as
+/// long as the backend is alive, so two processes can't corrupt it by
+/// writing to it at once; a second `open` on an already-locked path fails
+/// immediately with an `Err` rather than blocking. `checkpoint` compacts the
+/// log down to one record per live entry (dropping tombstones, since their
+/// effect is already baked into the snapshot) and `fsync`s the result.
+pub struct FileBackend {
+    records: BTreeMap<TableEntry, Vec<u8>>,
+    /// The handle `open` locked with `try_lock_exclusive`. Kept for the
+    /// whole life of the backend (rather than just inside `open`) and
+    /// re-cloned for both `writer` and `checkpoint`'s rewrite, since an
+    /// advisory lock belongs to the underlying open file description: a
+    /// `try_clone`'d fd still holds it, but a handle from a fresh
+    /// `OpenOptions::open` call does not.
+    lock: File,
+    writer: BufWriter<File>,
+    path: PathBuf,
+}
+
+impl FileBackend {
+    /// Opens (creating if necessary) the log at `path`, replaying any
+    /// existing records to rebuild the in-memory view, and locks the file
+    /// against concurrent access from other processes. Returns an `Err`
+    /// immediately (rather than blocking) if the path is already locked by
+    /// another `FileBackend`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileBackend> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("opening storage log at {}", path.display()))?;
+        file.try_lock_exclusive()
+            .with_context(|| format!("storage log at {} is locked by another FileBackend", path.display()))?;
+
+        let mut contents = Vec::new();
+        BufReader::new(file.try_clone()?).read_to_end(&mut contents)?;
+        let mut records = BTreeMap::new();
+        for record in serde_json::Deserializer::from_slice(&contents).into_iter::<Record>() {
+            let record = record?;
+            if record.deleted {
+                records.remove(&record.entry);
+            } else {
+                records.insert(record.entry, record.bytes);
+            }
+        }
+
+        let writer = BufWriter::new(file.try_clone()?);
+        Ok(FileBackend {
+            records,
+            lock: file,
+            writer,
+            path,
+        })
+    }
+
+    fn append(&mut self, record: &Record) {
+        serde_json::to_writer(&mut self.writer, record).unwrap();
+        self.writer.flush().unwrap();
+    }
+}
+
+impl StorageBackend for FileBackend {
+    fn get(&self, entry: &TableEntry) -> Option<Vec<u8>> {
+        self.records.get(entry).cloned()
+    }
+
+    fn contains(&self, entry: &TableEntry) -> bool {
+        self.records.contains_key(entry)
+    }
+
+    fn put(&mut self, entry: TableEntry, bytes: Vec<u8>) {
+        self.append(&Record { entry: entry.clone(), bytes: bytes.clone(), deleted: false });
+        self.records.insert(entry, bytes);
+    }
+
+    fn remove(&mut self, entry: &TableEntry) -> Option<Vec<u8>> {
+        self.append(&Record { entry: entry.clone(), bytes: Vec::new(), deleted: true });
+        self.records.remove(entry)
+    }
+
+    fn keys(&self) -> Vec<TableEntry> {
+        self.records.keys().cloned().collect()
+    }
+
+    fn range_prefix(&self, table_id: u64, prefix: &[u8]) -> Vec<(TableEntry, Vec<u8>)> {
+        range_prefix_in(&self.records, table_id, prefix)
+    }
+
+    fn checkpoint(&mut self) -> Result<()> {
+        self.writer.flush()
+            .with_context(|| format!("checkpointing storage log at {}", self.path.display()))?;
+        self.lock.set_len(0)
+            .with_context(|| format!("checkpointing storage log at {}", self.path.display()))?;
+        self.lock.seek(SeekFrom::Start(0))
+            .with_context(|| format!("checkpointing storage log at {}", self.path.display()))?;
+
+        let mut writer = BufWriter::new(
+            self.lock.try_clone()
+                .with_context(|| format!("checkpointing storage log at {}", self.path.display()))?,
+        );
+        for (entry, bytes) in &self.records {
+            let record = Record { entry: entry.clone(), bytes: bytes.clone(), deleted: false };
+            serde_json::to_writer(&mut writer, &record)?;
+        }
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+
+        // `self.lock`'s file offset now sits at the end of what we just
+        // wrote (clones of a `File` share the underlying open file
+        // description, including its position), so a fresh clone for
+        // `writer` picks up appending from exactly the right place —
+        // without ever closing the handle that holds the lock.
+        self.writer = BufWriter::new(
+            self.lock.try_clone()
+                .with_context(|| format!("reopening storage log at {}", self.path.display()))?,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// A path under the system temp dir unique to this process and test, so
+    /// parallel test runs never collide on the same log file.
+    fn temp_log_path(name: &str) -> PathBuf {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        std::env::temp_dir().join(format!("table-filebackend-test-{}-{name}-{n}.log", std::process::id()))
+    }
+
+    #[test]
+    fn open_replays_records_written_before_a_restart() {
+        let path = temp_log_path("replay");
+        {
+            let mut backend = FileBackend::open(&path).unwrap();
+            backend.put(TableEntry { id: 0, key: b"a".to_vec() }, b"1".to_vec());
+            backend.put(TableEntry { id: 0, key: b"b".to_vec() }, b"2".to_vec());
+            backend.remove(&TableEntry { id: 0, key: b"a".to_vec() });
+        }
+
+        let reopened = FileBackend::open(&path).unwrap();
+        assert_eq!(reopened.get(&TableEntry { id: 0, key: b"a".to_vec() }), None);
+        assert_eq!(reopened.get(&TableEntry { id: 0, key: b"b".to_vec() }), Some(b"2".to_vec()));
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn checkpoint_compacts_the_log_without_losing_live_entries() {
+        let path = temp_log_path("checkpoint");
+        {
+            let mut backend = FileBackend::open(&path).unwrap();
+            backend.put(TableEntry { id: 0, key: b"a".to_vec() }, b"1".to_vec());
+            backend.put(TableEntry { id: 0, key: b"b".to_vec() }, b"2".to_vec());
+            backend.remove(&TableEntry { id: 0, key: b"a".to_vec() });
+            backend.checkpoint().unwrap();
+
+            // A put after checkpoint should still append correctly, proving
+            // `writer` was rewired to follow the truncated-and-rewritten
+            // file rather than left pointing at stale offsets.
+            backend.put(TableEntry { id: 0, key: b"c".to_vec() }, b"3".to_vec());
+        }
+
+        let reopened = FileBackend::open(&path).unwrap();
+        assert_eq!(reopened.get(&TableEntry { id: 0, key: b"a".to_vec() }), None);
+        assert_eq!(reopened.get(&TableEntry { id: 0, key: b"b".to_vec() }), Some(b"2".to_vec()));
+        assert_eq!(reopened.get(&TableEntry { id: 0, key: b"c".to_vec() }), Some(b"3".to_vec()));
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn open_on_an_already_locked_path_fails_instead_of_blocking() {
+        let path = temp_log_path("lock");
+        let _held = FileBackend::open(&path).unwrap();
+
+        assert!(FileBackend::open(&path).is_err());
+
+        std::fs::remove_file(&path).ok();
+    }
+}
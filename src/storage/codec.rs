@@ -0,0 +1,109 @@
+use anyhow::Result;
+
+use super::TableValue;
+
+/// A pluggable serialization backend for [`Storage`](super::Storage).
+///
+/// A `Codec` is only used for *values*; `TableEntry` keys always go through
+/// a fixed canonical encoding (see `TableEntry::from_key`) so that identical
+/// keys hash to the same entry regardless of which codec a `Storage` was
+/// built with.
+pub trait Codec: Send + Sync {
+    fn serialize(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>>;
+
+    fn deserialize_value<V: TableValue>(&self, bytes: &[u8]) -> Result<V>;
+}
+
+/// The default codec: human-readable, widely compatible, and what `Storage`
+/// used exclusively before codecs became pluggable.
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn serialize(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>> {
+        let mut writer = Vec::new();
+        let mut json_serializer = serde_json::Serializer::new(&mut writer);
+        let mut erased_json_serializer = <dyn erased_serde::Serializer>::erase(&mut json_serializer);
+        value.erased_serialize(&mut erased_json_serializer)?;
+        Ok(writer)
+    }
+
+    fn deserialize_value<V: TableValue>(&self, bytes: &[u8]) -> Result<V> {
+        let mut de = serde_json::Deserializer::from_slice(bytes);
+        Ok(V::deserialize(&mut de)?)
+    }
+}
+
+/// MessagePack codec, considerably more compact than JSON for a
+/// byte-oriented store. Requires the `msgpack` feature.
+#[cfg(feature = "msgpack")]
+pub struct MsgPackCodec;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MsgPackCodec {
+    fn serialize(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>> {
+        let mut writer = Vec::new();
+        let mut rmp_serializer = rmp_serde::Serializer::new(&mut writer);
+        let mut erased_rmp_serializer = <dyn erased_serde::Serializer>::erase(&mut rmp_serializer);
+        value.erased_serialize(&mut erased_rmp_serializer)?;
+        Ok(writer)
+    }
+
+    fn deserialize_value<V: TableValue>(&self, bytes: &[u8]) -> Result<V> {
+        let mut de = rmp_serde::Deserializer::new(bytes);
+        Ok(V::deserialize(&mut de)?)
+    }
+}
+
+/// CBOR codec: also compact, and self-describing unlike MessagePack.
+/// Requires the `cbor` feature.
+#[cfg(feature = "cbor")]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl Codec for CborCodec {
+    fn serialize(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>> {
+        let mut writer = Vec::new();
+        let mut cbor_serializer = serde_cbor::Serializer::new(&mut writer);
+        let mut erased_cbor_serializer = <dyn erased_serde::Serializer>::erase(&mut cbor_serializer);
+        value.erased_serialize(&mut erased_cbor_serializer)?;
+        Ok(writer)
+    }
+
+    fn deserialize_value<V: TableValue>(&self, bytes: &[u8]) -> Result<V> {
+        let mut de = serde_cbor::Deserializer::from_slice(bytes);
+        Ok(V::deserialize(&mut de)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+
+    use super::super::TableId;
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_codec_round_trips_a_value() {
+        use super::super::Storage;
+        use super::MsgPackCodec;
+
+        const NAME_TABLE_ID: TableId<(), String> = TableId { id: 0, key: PhantomData, value: PhantomData };
+
+        let mut store = Storage::with_codec(MsgPackCodec);
+        store.put_table_entry(&NAME_TABLE_ID, (), "hello".to_string()).unwrap();
+        assert_eq!(store.borrow_table_entry(&NAME_TABLE_ID, &()).unwrap(), "hello");
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_codec_round_trips_a_value() {
+        use super::super::Storage;
+        use super::CborCodec;
+
+        const NAME_TABLE_ID: TableId<(), String> = TableId { id: 0, key: PhantomData, value: PhantomData };
+
+        let mut store = Storage::with_codec(CborCodec);
+        store.put_table_entry(&NAME_TABLE_ID, (), "hello".to_string()).unwrap();
+        assert_eq!(store.borrow_table_entry(&NAME_TABLE_ID, &()).unwrap(), "hello");
+    }
+}
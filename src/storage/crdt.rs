@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, collections::HashSet, hash::Hash};
+
+/// A value that can absorb another replica's copy of itself without losing
+/// data, for use with `Storage::merge_table_entry` in place of the
+/// last-write-wins clobber that `put_table_entry` performs.
+///
+/// Implementations must make `merge` commutative, associative, and
+/// idempotent so replicas converge to the same value regardless of the
+/// order writes are observed in.
+pub trait Mergeable {
+    fn merge(&mut self, other: Self);
+}
+
+/// Last-write-wins register: keeps whichever value carries the higher
+/// `stamp`. Ties on `stamp` are broken by keeping the greater `value`, so
+/// `merge` stays commutative and idempotent even when two writes share a
+/// stamp.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Lww<T> {
+    pub value: T,
+    pub stamp: u64,
+}
+
+impl<T> Lww<T> {
+    pub fn new(value: T, stamp: u64) -> Lww<T> {
+        Lww { value, stamp }
+    }
+}
+
+impl<T: Ord> Mergeable for Lww<T> {
+    fn merge(&mut self, other: Lww<T>) {
+        if other.stamp > self.stamp || (other.stamp == self.stamp && other.value > self.value) {
+            self.value = other.value;
+            self.stamp = other.stamp;
+        }
+    }
+}
+
+/// Grow-only set: merging is simply the union of both replicas' elements.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GSet<T: Eq + Hash> {
+    elements: HashSet<T>,
+}
+
+impl<T: Eq + Hash> GSet<T> {
+    pub fn new() -> GSet<T> {
+        GSet { elements: HashSet::new() }
+    }
+
+    pub fn insert(&mut self, value: T) {
+        self.elements.insert(value);
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.elements.contains(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.elements.iter()
+    }
+}
+
+impl<T: Eq + Hash> Mergeable for GSet<T> {
+    fn merge(&mut self, other: GSet<T>) {
+        self.elements.extend(other.elements);
+    }
+}
+
+/// Grow-only counter: tracks per-replica counts and merges by taking the
+/// max seen for each replica id, so the same increment can never be
+/// double-counted or lost depending on merge order.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GCounter {
+    counts: HashMap<u64, u64>,
+}
+
+impl GCounter {
+    pub fn new() -> GCounter {
+        GCounter::default()
+    }
+
+    pub fn increment(&mut self, replica_id: u64, amount: u64) {
+        *self.counts.entry(replica_id).or_insert(0) += amount;
+    }
+
+    pub fn value(&self) -> u64 {
+        self.counts.values().sum()
+    }
+}
+
+impl Mergeable for GCounter {
+    fn merge(&mut self, other: GCounter) {
+        for (replica_id, count) in other.counts {
+            let entry = self.counts.entry(replica_id).or_insert(0);
+            *entry = (*entry).max(count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gcounter_merge_converges_regardless_of_order() {
+        let mut a = GCounter::new();
+        a.increment(1, 5);
+        let mut b = GCounter::new();
+        b.increment(2, 3);
+
+        let mut merged_ab = a.clone();
+        merged_ab.merge(b.clone());
+        let mut merged_ba = b.clone();
+        merged_ba.merge(a.clone());
+
+        assert_eq!(merged_ab.value(), 8);
+        assert_eq!(merged_ab.value(), merged_ba.value());
+    }
+
+    #[test]
+    fn lww_merge_converges_regardless_of_order() {
+        let early = Lww::new("first", 1);
+        let late = Lww::new("second", 2);
+
+        let mut merged_forward = early.clone();
+        merged_forward.merge(late.clone());
+        let mut merged_backward = late.clone();
+        merged_backward.merge(early.clone());
+
+        assert_eq!(merged_forward.value, "second");
+        assert_eq!(merged_forward.value, merged_backward.value);
+        assert_eq!(merged_forward.stamp, merged_backward.stamp);
+    }
+}
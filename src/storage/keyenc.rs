@@ -0,0 +1,360 @@
+use std::fmt;
+
+use serde::{ser, ser::Error as _, Serialize};
+
+/// Encodes `key` into bytes whose lexicographic (byte-wise) order matches
+/// the key's natural order, so `TableEntry`s can live in a `BTreeMap` and
+/// be scanned by range or prefix.
+///
+/// Integers are encoded big-endian, with the sign bit flipped for signed
+/// types so negatives sort before positives; floats use the same
+/// flip-or-invert trick well-known from other order-preserving encoders
+/// (e.g. LevelDB-style key schemes). Strings, byte slices, and `char`s are
+/// variable-length, so (unlike the fixed-width types above) they can't just
+/// be concatenated when part of a composite key: two distinct keys like
+/// `("ab", "cd")` and `("a", "bcd")` would otherwise both encode to `abcd`
+/// and silently alias to the same `TableEntry`. To keep every variable-
+/// length field self-delimiting regardless of what follows it, each is
+/// escaped (a literal `0x00` byte becomes `0x00 0x01`) and terminated with
+/// `0x00 0x00`, the standard trick for order-preserving "memcomparable"
+/// encodings — see `write_escaped`. Composite keys made of fixed-width
+/// fields plus strings/bytes/chars in any position therefore sort
+/// correctly and never collide; a `Vec` of variable-length *count* (rather
+/// than variable-length elements) can still alias against a following
+/// field the same way, since sequences carry no length prefix.
+pub fn encode_ordered_key<K: Serialize>(key: &K) -> anyhow::Result<Vec<u8>> {
+    let mut serializer = KeyEncoder { output: Vec::new() };
+    key.serialize(&mut serializer)?;
+    Ok(serializer.output)
+}
+
+/// Appends `bytes` to `output` such that the result is self-delimiting:
+/// any literal `0x00` byte is escaped to `0x00 0x01`, and the whole run is
+/// terminated with `0x00 0x00`. Since `0x00 0x00 < 0x00 0x01 < 0x00 <other>`,
+/// this preserves lexicographic order (a terminated prefix always sorts
+/// before anything that continues past it) while making sure two different
+/// byte runs concatenated with whatever comes next never produce the same
+/// bytes as each other.
+fn write_escaped(output: &mut Vec<u8>, bytes: &[u8]) {
+    for &b in bytes {
+        if b == 0x00 {
+            output.push(0x00);
+            output.push(0x01);
+        } else {
+            output.push(b);
+        }
+    }
+    output.push(0x00);
+    output.push(0x00);
+}
+
+#[derive(Debug)]
+struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+struct KeyEncoder {
+    output: Vec<u8>,
+}
+
+impl ser::Serializer for &mut KeyEncoder {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        self.output.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.serialize_u8((v as u8) ^ 0x80)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.serialize_u16((v as u16) ^ 0x8000)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.serialize_u32((v as u32) ^ 0x8000_0000)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.serialize_u64((v as u64) ^ 0x8000_0000_0000_0000)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.output.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        let bits = v.to_bits();
+        let ordered = if bits & 0x8000_0000 != 0 { !bits } else { bits | 0x8000_0000 };
+        self.output.extend_from_slice(&ordered.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        let bits = v.to_bits();
+        let ordered = if bits & 0x8000_0000_0000_0000 != 0 { !bits } else { bits | 0x8000_0000_0000_0000 };
+        self.output.extend_from_slice(&ordered.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i128(self, _: i128) -> Result<(), Error> {
+        Err(Error::custom("ordered key encoding does not support i128"))
+    }
+
+    fn serialize_u128(self, _: u128) -> Result<(), Error> {
+        Err(Error::custom("ordered key encoding does not support u128"))
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        let mut buf = [0u8; 4];
+        write_escaped(&mut self.output, v.encode_utf8(&mut buf).as_bytes());
+        Ok(())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        write_escaped(&mut self.output, v.as_bytes());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        write_escaped(&mut self.output, v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.output.push(0);
+        Ok(())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), Error> {
+        self.output.push(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), Error> {
+        self.serialize_u32(variant_index)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.serialize_u32(variant_index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(Error::custom("ordered key encoding does not support maps"))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+}
+
+impl ser::SerializeSeq for &mut KeyEncoder {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut KeyEncoder {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut KeyEncoder {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for &mut KeyEncoder {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for &mut KeyEncoder {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, _key: &T) -> Result<(), Error> {
+        Err(Error::custom("ordered key encoding does not support maps"))
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, _value: &T) -> Result<(), Error> {
+        Err(Error::custom("ordered key encoding does not support maps"))
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Err(Error::custom("ordered key encoding does not support maps"))
+    }
+}
+
+impl ser::SerializeStruct for &mut KeyEncoder {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut KeyEncoder {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,18 @@
+use anyhow::Result;
+
+use super::TableValue;
+
+/// A `TableValue` whose on-disk layout may change over time.
+///
+/// `VERSION` is the layout's current version; `migrate` upgrades bytes
+/// encoded by an older version up to `VERSION`. `Storage` calls it once
+/// with the version the bytes were actually stored at; `migrate` is
+/// responsible for chaining through any intermediate versions itself
+/// (e.g. by matching on `old_version` and re-applying one upgrade step at
+/// a time) so each on-disk version only needs a migration from the one
+/// right before it.
+pub trait Versioned: TableValue + Sized {
+    const VERSION: u32;
+
+    fn migrate(old_version: u32, bytes: &[u8]) -> Result<Self>;
+}
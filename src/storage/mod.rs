@@ -0,0 +1,851 @@
+#![allow(dead_code, unused_imports)]
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer, de::DeserializeOwned, Serialize};
+use std::{
+    any::{TypeId},
+    collections::{BTreeMap, HashMap, HashSet},
+    marker::PhantomData,
+    path::Path,
+};
+
+mod backend;
+mod codec;
+mod crdt;
+mod keyenc;
+mod migration;
+
+pub use backend::{FileBackend, MemoryBackend, StorageBackend};
+pub use codec::{Codec, JsonCodec};
+pub use crdt::{GCounter, GSet, Lww, Mergeable};
+pub use migration::Versioned;
+#[cfg(feature = "msgpack")]
+pub use codec::MsgPackCodec;
+#[cfg(feature = "cbor")]
+pub use codec::CborCodec;
+
+pub trait TableValue: erased_serde::Serialize + Send + Sync + 'static {
+    fn type_id(&self, _: private::Internal) -> TypeId {
+        TypeId::of::<Self>()
+    }
+
+    /// A stable tag for this type, persisted alongside every value so that
+    /// `ensure_cached_table_entry` can reject a `TableId` reused with the
+    /// wrong `V` with a typed error, instead of deserializing the wrong
+    /// shape and handing back an unsound downcast. Defaults to
+    /// `std::any::type_name`, which is stable for the lifetime of a build
+    /// but not guaranteed across compiler versions. Every `Serialize +
+    /// DeserializeOwned` type gets `TableValue` from the blanket impl below
+    /// and so always uses this default; only a type that implements
+    /// `TableValue` by hand can override it.
+    fn type_oid() -> &'static str
+    where
+        Self: Sized,
+    {
+        std::any::type_name::<Self>()
+    }
+
+    fn deserialize<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        Self: Sized,
+        D: Deserializer<'de>;
+}
+
+impl dyn TableValue {
+    pub fn is<T: TableValue>(&self) -> bool {
+        let t = TypeId::of::<T>();
+        let boxed = self.type_id(private::Internal);
+        t == boxed
+    }
+
+    pub fn downcast_ref<T: TableValue>(&self) -> Option<&T> {
+        if self.is::<T>() {
+            unsafe { Some(&*(self as *const dyn TableValue as *const T)) }
+        } else {
+            None
+        }
+    }
+
+    pub fn downcast_mut<T: TableValue>(&mut self) -> Option<&mut T> {
+        if self.is::<T>() {
+            unsafe { Some(&mut *(self as *mut dyn TableValue as *mut T)) }
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> TableValue for T
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn deserialize<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        Self: Sized,
+        D: Deserializer<'de>,
+    {
+        Deserialize::deserialize(deserializer)
+    }
+}
+
+pub struct TableId<K: Serialize, V: TableValue> {
+    pub id: u64,
+    pub key: PhantomData<K>,
+    pub value: PhantomData<V>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub(crate) struct TableEntry {
+    pub(crate) id: u64,
+    pub(crate) key: Vec<u8>,
+}
+
+impl TableEntry {
+    /// Encodes `key` into the bytes used to identify this entry in both
+    /// `entries` and `database`.
+    ///
+    /// This always goes through the order-preserving encoding in `keyenc`,
+    /// independent of whichever `Codec` the `Storage` was built with: key
+    /// encoding must be deterministic so that the same logical key always
+    /// produces the same `TableEntry`, and order-stable (lexicographic byte
+    /// order matches the key's natural order) so entries can be kept in a
+    /// `BTreeMap` and served by `scan_prefix`/`iter_table`.
+    fn from_key<K: Serialize>(table_id: u64, key: &K) -> Result<TableEntry> {
+        Ok(TableEntry {
+            id: table_id,
+            key: keyenc::encode_ordered_key(key)?,
+        })
+    }
+}
+
+/// The on-disk header for a value: its `type_oid`, format `version`, and the
+/// original key (JSON-encoded, so it can be recovered by `iter_table`/
+/// `scan_prefix` even though `TableEntry::key` itself is a one-way,
+/// order-preserving encoding), followed by the value's codec-encoded bytes.
+/// Always JSON-wrapped, independent of the `Codec` in use, so the header
+/// can be read before the payload's shape is known. `version` is `0` for
+/// values written through the plain (non-`Versioned`) API.
+#[derive(Serialize, Deserialize)]
+struct TaggedValue {
+    type_oid: String,
+    version: u32,
+    key: Vec<u8>,
+    bytes: Vec<u8>,
+}
+
+impl TaggedValue {
+    fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap()
+    }
+
+    fn decode(bytes: &[u8]) -> Result<TaggedValue> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Metadata tracked for every entry cached in `entries`, mirroring what's
+/// persisted in its `TaggedValue` header. Kept alongside `entries` rather
+/// than inside it because a `Box<dyn TableValue>` alone can't tell
+/// `flush_one` its own `type_oid`/`version`/original key.
+#[derive(Clone)]
+struct EntryMeta {
+    type_oid: String,
+    version: u32,
+    key: Vec<u8>,
+}
+
+/// `Storage` is generic over its `Codec` rather than storing a `Box<dyn
+/// Codec>`: `Codec::deserialize_value` is generic over `V`, which makes
+/// `Codec` itself not object-safe (a `dyn Codec` could never dispatch it).
+/// Defaulting the parameter to `JsonCodec` keeps `Storage` usable without
+/// naming it for callers happy with the default.
+pub struct Storage<C: Codec = JsonCodec> {
+    entries: BTreeMap<TableEntry, Box<dyn TableValue>>,
+    meta: HashMap<TableEntry, EntryMeta>,
+    database: Box<dyn StorageBackend>,
+    /// Keys handed out via `borrow_table_entry_mut` that may have been
+    /// mutated in place and haven't been re-serialized into `database` yet.
+    dirty: HashSet<TableEntry>,
+    codec: C,
+}
+
+impl Storage<JsonCodec> {
+    pub fn new() -> Storage<JsonCodec> {
+        Storage::with_codec(JsonCodec)
+    }
+
+    /// Opens a disk-backed `Storage` at `path`, replaying its write-ahead
+    /// log to rebuild `database`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Storage<JsonCodec>> {
+        Ok(Storage::with_backend(JsonCodec, Box::new(FileBackend::open(path)?)))
+    }
+}
+
+impl<C: Codec> Storage<C> {
+    /// Builds a `Storage` that serializes values with `codec` instead of the
+    /// default JSON codec. Entries still live only in memory.
+    pub fn with_codec(codec: C) -> Storage<C> {
+        Storage::with_backend(codec, Box::new(MemoryBackend::new()))
+    }
+
+    /// Builds a `Storage` from an explicit codec and backend. The in-memory
+    /// behavior used by tests is `with_codec`/`new`, which default to
+    /// `MemoryBackend`.
+    pub fn with_backend(codec: C, backend: Box<dyn StorageBackend>) -> Storage<C> {
+        Storage {
+            entries: BTreeMap::new(),
+            meta: HashMap::new(),
+            database: backend,
+            dirty: HashSet::new(),
+            codec,
+        }
+    }
+
+    /// Compacts the backing store, if it supports compaction (see
+    /// `StorageBackend::checkpoint`).
+    pub fn checkpoint(&mut self) -> Result<()> {
+        self.database.checkpoint()
+    }
+
+    /// Re-serializes a single cached entry into `database`, if it is cached.
+    fn flush_one(&mut self, table_entry: &TableEntry) {
+        if let Some(value) = self.entries.get(table_entry) {
+            let meta = self.meta.get(table_entry).expect("cached entry missing its meta").clone();
+            let bytes = self.codec.serialize(&**value).unwrap();
+            let tagged = TaggedValue { type_oid: meta.type_oid, version: meta.version, key: meta.key, bytes };
+            self.database.put(table_entry.clone(), tagged.encode());
+        }
+    }
+
+    /// Re-serializes every dirty entry back into `database` and clears the
+    /// dirty set, so that in-place mutations made through
+    /// `borrow_table_entry_mut` are reflected in the byte store.
+    pub fn flush(&mut self) {
+        let dirty: Vec<TableEntry> = self.dirty.drain().collect();
+        for table_entry in dirty {
+            self.flush_one(&table_entry);
+        }
+    }
+
+    /// Flushes a single key, if it is dirty. Cheaper than `flush` when only
+    /// one entry is known to have changed.
+    pub fn flush_entry<
+        K: Serialize,
+        V: TableValue,
+    >(&mut self, table_id: &TableId<K, V>, key: &K) -> Result<()> {
+        let table_entry = TableEntry::from_key(table_id.id, key)?;
+        if self.dirty.remove(&table_entry) {
+            self.flush_one(&table_entry);
+        }
+        Ok(())
+    }
+
+    fn ensure_cached_table_entry<
+        V: TableValue,
+    >(
+        &mut self,
+        table_entry: &TableEntry,
+    ) -> Result<()> {
+        if let Some(meta) = self.meta.get(table_entry) {
+            return Self::check_type_oid::<V>(table_entry, &meta.type_oid);
+        }
+        let bytes = match self.database.get(table_entry) {
+            Some(bytes) => bytes,
+            None => return Ok(()),
+        };
+        let tagged = TaggedValue::decode(&bytes)?;
+        Self::check_type_oid::<V>(table_entry, &tagged.type_oid)?;
+        let value: V = self.codec.deserialize_value(&tagged.bytes)?;
+        self.entries.insert(table_entry.clone(), Box::new(value));
+        self.meta.insert(
+            table_entry.clone(),
+            EntryMeta { type_oid: tagged.type_oid, version: tagged.version, key: tagged.key },
+        );
+        Ok(())
+    }
+
+    /// Common OID check shared by `ensure_cached_table_entry` and
+    /// `ensure_cached_table_entry_versioned`, on both the cold path (an
+    /// entry freshly decoded from `database`) and the warm path (an entry
+    /// already resident in `entries`/`meta`) — checking only the cold path
+    /// let a mismatched `V` for a warm entry skip straight past this check
+    /// and panic inside `downcast_ref::<V>().unwrap()` instead.
+    fn check_type_oid<V: TableValue>(table_entry: &TableEntry, type_oid: &str) -> Result<()> {
+        if type_oid != V::type_oid() {
+            return Err(anyhow!(
+                "table entry {:?} holds a `{}`, not the requested `{}`",
+                table_entry,
+                type_oid,
+                V::type_oid(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn contains_table_entry<
+        K: Serialize,
+        V: TableValue,
+    >(
+        &mut self,
+        table_id: &TableId<K, V>,
+        key: &K,
+    ) -> Result<bool> {
+        let table_entry = TableEntry::from_key(table_id.id, &key)?;
+        if self.entries.contains_key(&table_entry) {
+            return Ok(true);
+        }
+        if self.database.contains(&table_entry) {
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    pub fn put_table_entry<
+        K: Serialize,
+        V: TableValue,
+    >(&mut self, table_id: &TableId<K, V>, key: K, value: V) -> Result<()> {
+        let table_entry = TableEntry::from_key(table_id.id, &key)?;
+        let key_json = serde_json::to_vec(&key).unwrap();
+        let bytes = self.codec.serialize(&value).unwrap();
+        let tagged = TaggedValue {
+            type_oid: V::type_oid().to_string(),
+            version: 0,
+            key: key_json.clone(),
+            bytes,
+        };
+        self.database.put(table_entry.clone(), tagged.encode());
+        self.dirty.remove(&table_entry);
+        self.meta.insert(
+            table_entry.clone(),
+            EntryMeta { type_oid: V::type_oid().to_string(), version: 0, key: key_json },
+        );
+        self.entries.insert(table_entry, Box::new(value));
+        Ok(())
+    }
+
+    fn ensure_cached_table_entry_versioned<
+        V: Versioned,
+    >(
+        &mut self,
+        table_entry: &TableEntry,
+    ) -> Result<()> {
+        if let Some(meta) = self.meta.get(table_entry) {
+            return Self::check_type_oid::<V>(table_entry, &meta.type_oid);
+        }
+        let bytes = match self.database.get(table_entry) {
+            Some(bytes) => bytes,
+            None => return Ok(()),
+        };
+        let tagged = TaggedValue::decode(&bytes)?;
+        Self::check_type_oid::<V>(table_entry, &tagged.type_oid)?;
+        let value = if tagged.version < V::VERSION {
+            V::migrate(tagged.version, &tagged.bytes)?
+        } else {
+            self.codec.deserialize_value(&tagged.bytes)?
+        };
+        self.entries.insert(table_entry.clone(), Box::new(value));
+        self.meta.insert(
+            table_entry.clone(),
+            EntryMeta { type_oid: tagged.type_oid, version: V::VERSION, key: tagged.key },
+        );
+        if tagged.version < V::VERSION {
+            self.dirty.insert(table_entry.clone());
+        }
+        Ok(())
+    }
+
+    /// Like `put_table_entry`, but for a `Versioned` value: stamps the
+    /// stored record with `V::VERSION` so a later load can tell whether it
+    /// needs to run through `V::migrate`.
+    pub fn put_versioned_table_entry<
+        K: Serialize,
+        V: Versioned,
+    >(&mut self, table_id: &TableId<K, V>, key: K, value: V) -> Result<()> {
+        let table_entry = TableEntry::from_key(table_id.id, &key)?;
+        let key_json = serde_json::to_vec(&key).unwrap();
+        let bytes = self.codec.serialize(&value).unwrap();
+        let tagged = TaggedValue {
+            type_oid: V::type_oid().to_string(),
+            version: V::VERSION,
+            key: key_json.clone(),
+            bytes,
+        };
+        self.database.put(table_entry.clone(), tagged.encode());
+        self.dirty.remove(&table_entry);
+        self.meta.insert(
+            table_entry.clone(),
+            EntryMeta { type_oid: V::type_oid().to_string(), version: V::VERSION, key: key_json },
+        );
+        self.entries.insert(table_entry, Box::new(value));
+        Ok(())
+    }
+
+    /// Like `borrow_table_entry`, but for a `Versioned` value: transparently
+    /// upgrades an entry stored at an older `VERSION` through `V::migrate`
+    /// and marks it dirty so the upgraded form is written back on the next
+    /// flush.
+    pub fn borrow_versioned_table_entry<
+        K: Serialize,
+        V: Versioned,
+    >(
+        &mut self,
+        table_id: &TableId<K, V>,
+        key: &K,
+    ) -> Result<&V> {
+        let table_entry = TableEntry::from_key(table_id.id, &key)?;
+        self.ensure_cached_table_entry_versioned::<V>(&table_entry)?;
+        let entry = self.entries.get(&table_entry).unwrap();
+        Ok(entry.downcast_ref::<V>().unwrap())
+    }
+
+    /// Force-reads and upgrades every entry in `table_id`, for callers that
+    /// want eager rather than load-triggered migration. Flushes the
+    /// upgraded entries back to `database` before returning.
+    pub fn migrate_all<
+        K: Serialize,
+        V: Versioned,
+    >(&mut self, table_id: &TableId<K, V>) -> Result<()> {
+        let table_entries: Vec<TableEntry> = self.database.keys()
+            .into_iter()
+            .filter(|entry| entry.id == table_id.id)
+            .collect();
+        for table_entry in table_entries {
+            self.ensure_cached_table_entry_versioned::<V>(&table_entry)?;
+        }
+        self.flush();
+        Ok(())
+    }
+
+    /// Writes `value` for `key`, merging with any existing value instead of
+    /// overwriting it outright. Unlike `put_table_entry`, this is safe to
+    /// call from two replicas or two code paths writing the same key
+    /// concurrently: `V::merge` is required to be commutative, associative,
+    /// and idempotent, so whichever order the writes land in, the store
+    /// converges to the same result.
+    pub fn merge_table_entry<
+        K: Serialize,
+        V: TableValue + Mergeable,
+    >(&mut self, table_id: &TableId<K, V>, key: K, value: V) -> Result<()> {
+        let table_entry = TableEntry::from_key(table_id.id, &key)?;
+        self.ensure_cached_table_entry::<V>(&table_entry)?;
+        match self.entries.get_mut(&table_entry) {
+            Some(existing) => existing.downcast_mut::<V>().unwrap().merge(value),
+            None => {
+                let key_json = serde_json::to_vec(&key).unwrap();
+                self.meta.insert(
+                    table_entry.clone(),
+                    EntryMeta { type_oid: V::type_oid().to_string(), version: 0, key: key_json },
+                );
+                self.entries.insert(table_entry.clone(), Box::new(value));
+            }
+        }
+        self.flush_one(&table_entry);
+        self.dirty.remove(&table_entry);
+        Ok(())
+    }
+
+    pub fn borrow_table_entry<
+        K: Serialize,
+        V: TableValue,
+    >(
+        &mut self,
+        table_id: &TableId<K, V>,
+        key: &K,
+    ) -> Result<&V> {
+        let table_entry = TableEntry::from_key(table_id.id, &key)?;
+        self.ensure_cached_table_entry::<V>(&table_entry)?;
+        let entry = self.entries.get(&table_entry).unwrap();
+        Ok(entry.downcast_ref::<V>().unwrap())
+    }
+
+    pub fn borrow_table_entry_mut<
+        K: Serialize,
+        V: TableValue,
+    >(
+        &mut self,
+        table_id: &TableId<K, V>,
+        key: &K,
+    ) -> Result<&mut V> {
+        let table_entry = TableEntry::from_key(table_id.id, &key)?;
+        self.ensure_cached_table_entry::<V>(&table_entry)?;
+        self.dirty.insert(table_entry.clone());
+        let entry = self.entries.get_mut(&table_entry).unwrap();
+        Ok(entry.downcast_mut::<V>().unwrap())
+    }
+
+    /// Removes `key` from `table_id`, if present, from both the cache and
+    /// `database`.
+    pub fn remove_table_entry<
+        K: Serialize,
+        V: TableValue,
+    >(&mut self, table_id: &TableId<K, V>, key: &K) -> Result<()> {
+        let table_entry = TableEntry::from_key(table_id.id, key)?;
+        self.entries.remove(&table_entry);
+        self.meta.remove(&table_entry);
+        self.dirty.remove(&table_entry);
+        self.database.remove(&table_entry);
+        Ok(())
+    }
+
+    /// Every `(key, value)` pair in `table_id` whose key starts with
+    /// `prefix` (under the order-preserving encoding in `keyenc`), in
+    /// ascending key order. Flushes first, so a prior in-place mutation via
+    /// `borrow_table_entry_mut` is reflected in the result.
+    pub fn scan_prefix<
+        K: Serialize + DeserializeOwned,
+        V: TableValue,
+    >(
+        &mut self,
+        table_id: &TableId<K, V>,
+        prefix: &[u8],
+    ) -> Result<Vec<(K, V)>> {
+        self.flush();
+        let mut results = Vec::new();
+        for (table_entry, bytes) in self.database.range_prefix(table_id.id, prefix) {
+            let tagged = TaggedValue::decode(&bytes)?;
+            if tagged.type_oid != V::type_oid() {
+                return Err(anyhow!(
+                    "table entry {:?} holds a `{}`, not the requested `{}`",
+                    table_entry,
+                    tagged.type_oid,
+                    V::type_oid(),
+                ));
+            }
+            let key: K = serde_json::from_slice(&tagged.key)?;
+            let value: V = self.codec.deserialize_value(&tagged.bytes)?;
+            results.push((key, value));
+        }
+        Ok(results)
+    }
+
+    /// Every `(key, value)` pair in `table_id`, in ascending key order.
+    pub fn iter_table<
+        K: Serialize + DeserializeOwned,
+        V: TableValue,
+    >(&mut self, table_id: &TableId<K, V>) -> Result<Vec<(K, V)>> {
+        self.scan_prefix(table_id, &[])
+    }
+
+    /// Runs `f` against a `Transaction` staged on top of this `Storage`:
+    /// `put`/`remove`/`merge` calls made through it are buffered rather than
+    /// applied right away, and only land in `entries`/`database` if `f`
+    /// returns `Ok`. If `f` returns `Err`, every staged change is discarded
+    /// and the store is left exactly as it was — so a caller that needs to
+    /// update several keys together (e.g. move a value from one counter to
+    /// another) can bail out partway through with `?` instead of leaving the
+    /// store half-updated.
+    pub fn transaction<F, R>(&mut self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Transaction<C>) -> Result<R>,
+    {
+        let mut tx = Transaction { storage: self, staged: HashMap::new(), rolled_back: false };
+        let result = f(&mut tx)?;
+        if !tx.rolled_back {
+            tx.commit();
+        }
+        Ok(result)
+    }
+}
+
+/// What a `Transaction` will do to a `TableEntry` once committed.
+enum Staged {
+    Put(Box<dyn TableValue>, EntryMeta),
+    Remove,
+}
+
+/// A staged view of a `Storage`, handed to the closure passed to
+/// `Storage::transaction`. Writes made through a `Transaction` are buffered
+/// in `staged` and only applied to the underlying `Storage` by `commit`;
+/// reads check `staged` first and fall back to the underlying `Storage`, so
+/// a transaction sees its own uncommitted writes. Returning `Err` from the
+/// closure discards `staged` without applying it; calling `rollback`
+/// explicitly does the same while still letting the closure return `Ok`.
+pub struct Transaction<'a, C: Codec = JsonCodec> {
+    storage: &'a mut Storage<C>,
+    staged: HashMap<TableEntry, Staged>,
+    rolled_back: bool,
+}
+
+impl<'a, C: Codec> Transaction<'a, C> {
+    pub fn contains_table_entry<
+        K: Serialize,
+        V: TableValue,
+    >(&mut self, table_id: &TableId<K, V>, key: &K) -> Result<bool> {
+        let table_entry = TableEntry::from_key(table_id.id, key)?;
+        match self.staged.get(&table_entry) {
+            Some(Staged::Put(_, _)) => Ok(true),
+            Some(Staged::Remove) => Ok(false),
+            None => self.storage.contains_table_entry(table_id, key),
+        }
+    }
+
+    pub fn put_table_entry<
+        K: Serialize,
+        V: TableValue,
+    >(&mut self, table_id: &TableId<K, V>, key: K, value: V) -> Result<()> {
+        let table_entry = TableEntry::from_key(table_id.id, &key)?;
+        let key_json = serde_json::to_vec(&key).unwrap();
+        let meta = EntryMeta { type_oid: V::type_oid().to_string(), version: 0, key: key_json };
+        self.staged.insert(table_entry, Staged::Put(Box::new(value), meta));
+        Ok(())
+    }
+
+    pub fn remove_table_entry<
+        K: Serialize,
+        V: TableValue,
+    >(&mut self, table_id: &TableId<K, V>, key: &K) -> Result<()> {
+        let table_entry = TableEntry::from_key(table_id.id, key)?;
+        self.staged.insert(table_entry, Staged::Remove);
+        Ok(())
+    }
+
+    pub fn borrow_table_entry<
+        K: Serialize,
+        V: TableValue,
+    >(&mut self, table_id: &TableId<K, V>, key: &K) -> Result<Option<&V>> {
+        let table_entry = TableEntry::from_key(table_id.id, key)?;
+        match self.staged.get(&table_entry) {
+            Some(Staged::Put(value, _)) => Ok(value.downcast_ref::<V>()),
+            Some(Staged::Remove) => Ok(None),
+            None => {
+                self.storage.ensure_cached_table_entry::<V>(&table_entry)?;
+                Ok(self.storage.entries.get(&table_entry).and_then(|value| value.downcast_ref::<V>()))
+            }
+        }
+    }
+
+    /// Like `Storage::merge_table_entry`, but staged: the merged value isn't
+    /// written back until the transaction commits. Requires `V: Clone`
+    /// (unlike `Storage::merge_table_entry`) so the current value can be
+    /// read out of the overlay or cache, merged in memory, and staged back
+    /// without disturbing the original until commit.
+    pub fn merge_table_entry<
+        K: Serialize,
+        V: TableValue + Mergeable + Clone,
+    >(&mut self, table_id: &TableId<K, V>, key: K, value: V) -> Result<()> {
+        let table_entry = TableEntry::from_key(table_id.id, &key)?;
+        let mut current = match self.staged.get(&table_entry) {
+            Some(Staged::Put(existing, _)) => Some(existing.downcast_ref::<V>().unwrap().clone()),
+            Some(Staged::Remove) => None,
+            None => {
+                self.storage.ensure_cached_table_entry::<V>(&table_entry)?;
+                self.storage.entries.get(&table_entry).map(|existing| existing.downcast_ref::<V>().unwrap().clone())
+            }
+        };
+        match &mut current {
+            Some(existing) => existing.merge(value),
+            None => current = Some(value),
+        }
+        let key_json = serde_json::to_vec(&key).unwrap();
+        let meta = EntryMeta { type_oid: V::type_oid().to_string(), version: 0, key: key_json };
+        self.staged.insert(table_entry, Staged::Put(Box::new(current.unwrap()), meta));
+        Ok(())
+    }
+
+    /// Discards every change staged so far, leaving `storage` untouched.
+    /// Unlike returning `Err` from the `transaction` closure (which also
+    /// discards staged changes, via `storage.transaction` never calling
+    /// `commit`), `rollback` lets a caller bail out of a transaction while
+    /// still returning `Ok` — e.g. after a validation check decides there's
+    /// nothing to do after all.
+    pub fn rollback(&mut self) {
+        self.staged.clear();
+        self.rolled_back = true;
+    }
+
+    /// Applies every staged change to `storage`, exactly like the
+    /// non-transactional `put_table_entry`/`remove_table_entry` would have.
+    fn commit(self) {
+        for (table_entry, staged) in self.staged {
+            match staged {
+                Staged::Put(value, meta) => {
+                    let bytes = self.storage.codec.serialize(&*value).unwrap();
+                    let tagged = TaggedValue {
+                        type_oid: meta.type_oid.clone(),
+                        version: meta.version,
+                        key: meta.key.clone(),
+                        bytes,
+                    };
+                    self.storage.database.put(table_entry.clone(), tagged.encode());
+                    self.storage.dirty.remove(&table_entry);
+                    self.storage.meta.insert(table_entry.clone(), meta);
+                    self.storage.entries.insert(table_entry, value);
+                }
+                Staged::Remove => {
+                    self.storage.entries.remove(&table_entry);
+                    self.storage.meta.remove(&table_entry);
+                    self.storage.dirty.remove(&table_entry);
+                    self.storage.database.remove(&table_entry);
+                }
+            }
+        }
+    }
+}
+
+mod private {
+    #[derive(Debug)]
+    pub struct Internal;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNTER_TABLE_ID: TableId<(), u64> = TableId { id: 0, key: PhantomData, value: PhantomData };
+
+    #[test]
+    fn flush_persists_an_in_place_mutation() {
+        let mut store = Storage::new();
+        store.put_table_entry(&COUNTER_TABLE_ID, (), 3u64).unwrap();
+
+        {
+            let counter = store.borrow_table_entry_mut(&COUNTER_TABLE_ID, &()).unwrap();
+            *counter -= 1;
+        }
+
+        let table_entry = TableEntry::from_key(COUNTER_TABLE_ID.id, &()).unwrap();
+        assert!(store.dirty.contains(&table_entry));
+
+        store.flush();
+        assert!(store.dirty.is_empty());
+
+        // Read back what actually landed in `database`, bypassing the
+        // in-memory `entries` cache (which would reflect the mutation
+        // either way), to confirm the decrement was really written through.
+        let bytes = store.database.get(&table_entry).unwrap();
+        let tagged = TaggedValue::decode(&bytes).unwrap();
+        let persisted: u64 = store.codec.deserialize_value(&tagged.bytes).unwrap();
+        assert_eq!(persisted, 2);
+    }
+
+    const NAMES_TABLE_ID: TableId<String, u64> = TableId { id: 1, key: PhantomData, value: PhantomData };
+
+    #[test]
+    fn scan_prefix_and_remove() {
+        let mut store = Storage::new();
+        store.put_table_entry(&NAMES_TABLE_ID, "apple".to_string(), 1).unwrap();
+        store.put_table_entry(&NAMES_TABLE_ID, "apricot".to_string(), 2).unwrap();
+        store.put_table_entry(&NAMES_TABLE_ID, "banana".to_string(), 3).unwrap();
+
+        let mut matches = store.scan_prefix(&NAMES_TABLE_ID, b"ap").unwrap();
+        matches.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            matches,
+            vec![("apple".to_string(), 1), ("apricot".to_string(), 2)],
+        );
+
+        store.remove_table_entry(&NAMES_TABLE_ID, &"apple".to_string()).unwrap();
+        let remaining = store.scan_prefix(&NAMES_TABLE_ID, b"ap").unwrap();
+        assert_eq!(remaining, vec![("apricot".to_string(), 2)]);
+
+        let all = store.iter_table(&NAMES_TABLE_ID).unwrap();
+        assert_eq!(all.len(), 2);
+    }
+
+    #[test]
+    fn transaction_discards_staged_writes_on_error() {
+        let mut store = Storage::new();
+        store.put_table_entry(&COUNTER_TABLE_ID, (), 3u64).unwrap();
+
+        let result: Result<()> = store.transaction(|tx| {
+            tx.put_table_entry(&COUNTER_TABLE_ID, (), 99u64)?;
+            Err(anyhow!("whoops, bail out"))
+        });
+        assert!(result.is_err());
+        assert_eq!(*store.borrow_table_entry(&COUNTER_TABLE_ID, &()).unwrap(), 3);
+
+        store
+            .transaction(|tx| {
+                tx.put_table_entry(&COUNTER_TABLE_ID, (), 7u64)?;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(*store.borrow_table_entry(&COUNTER_TABLE_ID, &()).unwrap(), 7);
+    }
+
+    #[test]
+    fn transaction_rollback_discards_staged_writes_even_on_ok() {
+        let mut store = Storage::new();
+        store.put_table_entry(&COUNTER_TABLE_ID, (), 3u64).unwrap();
+
+        store
+            .transaction(|tx| {
+                tx.put_table_entry(&COUNTER_TABLE_ID, (), 99u64)?;
+                tx.rollback();
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(*store.borrow_table_entry(&COUNTER_TABLE_ID, &()).unwrap(), 3);
+    }
+
+    const WRONG_TYPE_TABLE_ID: TableId<(), String> = TableId { id: 0, key: PhantomData, value: PhantomData };
+
+    #[test]
+    fn borrow_table_entry_rejects_a_mismatched_type_even_when_cached() {
+        let mut store = Storage::new();
+        store.put_table_entry(&COUNTER_TABLE_ID, (), 7u64).unwrap();
+
+        // Warm the cache under the correct type first, so the OID mismatch
+        // below has to be caught on the cached (`meta`-only) path rather
+        // than the cold path that decodes straight from `database`.
+        store.borrow_table_entry(&COUNTER_TABLE_ID, &()).unwrap();
+
+        assert!(store.borrow_table_entry(&WRONG_TYPE_TABLE_ID, &()).is_err());
+    }
+
+    const I128_TABLE_ID: TableId<i128, u64> = TableId { id: 2, key: PhantomData, value: PhantomData };
+
+    #[test]
+    fn put_table_entry_reports_an_unencodable_key_instead_of_panicking() {
+        let mut store = Storage::new();
+        assert!(store.put_table_entry(&I128_TABLE_ID, 1i128, 1).is_err());
+    }
+
+    /// A value whose version 0 layout was a bare `String`, upgraded at
+    /// version 1 to wrap it in a struct.
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+    struct Name {
+        value: String,
+    }
+
+    impl Versioned for Name {
+        const VERSION: u32 = 1;
+
+        fn migrate(old_version: u32, bytes: &[u8]) -> Result<Name> {
+            match old_version {
+                0 => Ok(Name { value: serde_json::from_slice(bytes)? }),
+                other => Err(anyhow!("no migration from version {other}")),
+            }
+        }
+    }
+
+    const NAME_TABLE_ID: TableId<(), Name> = TableId { id: 3, key: PhantomData, value: PhantomData };
+
+    #[test]
+    fn borrow_versioned_table_entry_migrates_an_old_version_on_load() {
+        let mut store = Storage::new();
+
+        // Plant a fake version-0 record directly in `database`, bypassing
+        // `put_versioned_table_entry` (which would always write the current
+        // `VERSION`), to simulate a record written with `Name`'s version 0
+        // layout.
+        let table_entry = TableEntry::from_key(NAME_TABLE_ID.id, &()).unwrap();
+        let tagged = TaggedValue {
+            type_oid: Name::type_oid().to_string(),
+            version: 0,
+            key: serde_json::to_vec(&()).unwrap(),
+            bytes: serde_json::to_vec("legacy").unwrap(),
+        };
+        store.database.put(table_entry.clone(), tagged.encode());
+
+        let migrated = store.borrow_versioned_table_entry(&NAME_TABLE_ID, &()).unwrap();
+        assert_eq!(*migrated, Name { value: "legacy".to_string() });
+        assert!(store.dirty.contains(&table_entry));
+
+        store.flush();
+        let bytes = store.database.get(&table_entry).unwrap();
+        let rewritten = TaggedValue::decode(&bytes).unwrap();
+        assert_eq!(rewritten.version, Name::VERSION);
+    }
+}